@@ -0,0 +1,293 @@
+// SPDX-FileCopyrightText: 2018-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! An in-process, hardware-free [`CommandResponse`] backend that models enough of the MCP2210's
+//! register state (chip settings, GPIO value/direction, SPI transfer settings and data, NVRAM
+//! power-up defaults, EEPROM) to answer commands the same way real silicon would. Plugging this
+//! into [`Mcp2210::new`] lets the rest of the crate be exercised in tests without a physical
+//! device attached.
+
+use hidapi::HidResult;
+
+use crate::{Buffer, CommandResponse, BUFFER_SIZE};
+
+const EEPROM_SIZE: usize = 256;
+
+const ERR_UNKNOWN_COMMAND: u8 = 0xF9;
+
+/// SPI engine status byte: a fresh transfer has been started (the data this call carries hasn't
+/// been clocked out yet).
+const SPI_STATUS_STARTED: u8 = 0x10;
+/// SPI engine status byte: the transfer is complete; the data in this response is the last of it.
+const SPI_STATUS_FINISHED: u8 = 0x20;
+
+/// NVRAM sub-command parameter: SPI power-up transfer settings.
+const NVRAM_SPI_SETTINGS: u8 = 0x10;
+/// NVRAM sub-command parameter: chip power-up settings.
+const NVRAM_CHIP_SETTINGS: u8 = 0x20;
+
+/// Register state backing a virtual MCP2210.
+///
+/// Holds the same logical state a real chip keeps in SRAM/NVRAM: chip settings, GPIO
+/// value/direction bitmasks, SPI transfer settings and in-flight transfer data, NVRAM power-up
+/// defaults, and the EEPROM contents.
+pub struct Mcp2210Emulator {
+    chip_settings: [u8; 4],
+    gpio_value: u16,
+    gpio_direction: u16,
+    spi_settings: [u8; 18],
+    eeprom: [u8; EEPROM_SIZE],
+    nvram_chip_settings: [u8; 4],
+    nvram_spi_settings: [u8; 18],
+    // The MCP2210's SPI engine is full-duplex with a one-call pipeline delay: whatever bytes a
+    // `Transfer SPI Data` command clocks out are only echoed back (as the MISO data read over the
+    // same period) on the *next* call. This holds those not-yet-echoed bytes.
+    spi_pending: Vec<u8>,
+}
+
+impl Default for Mcp2210Emulator {
+    fn default() -> Self {
+        Mcp2210Emulator {
+            chip_settings: [0; 4],
+            gpio_value: 0x01FF,
+            gpio_direction: 0x01FF,
+            spi_settings: [0; 18],
+            eeprom: [0xFF; EEPROM_SIZE],
+            nvram_chip_settings: [0; 4],
+            nvram_spi_settings: [0; 18],
+            spi_pending: Vec::new(),
+        }
+    }
+}
+
+impl Mcp2210Emulator {
+    /// Creates a fresh emulator with the chip's documented power-on-reset defaults: all 9 GP
+    /// pins configured as inputs and their value latches high.
+    pub fn new() -> Self {
+        Mcp2210Emulator::default()
+    }
+
+    /// The current GPIO value bitmask (bit `n` is `GPn`), as set via `SET_GPIO_VALUE`.
+    pub fn gpio_value(&self) -> u16 {
+        self.gpio_value
+    }
+
+    /// The current GPIO direction bitmask (bit `n` is `GPn`; `1` means input), as set via
+    /// `SET_GPIO_DIRECTION`.
+    pub fn gpio_direction(&self) -> u16 {
+        self.gpio_direction
+    }
+}
+
+impl CommandResponse for Mcp2210Emulator {
+    fn command_response(&mut self, cmd: &Buffer, res: &mut Buffer) -> HidResult<()> {
+        *res = [0; BUFFER_SIZE];
+        res[0] = cmd[0];
+        match cmd[0] {
+            // Get GPIO Pin Direction
+            0x33 => {
+                res[4..6].copy_from_slice(&self.gpio_direction.to_le_bytes());
+            }
+            // Set GPIO Pin Direction
+            0x34 => {
+                self.gpio_direction = u16::from_le_bytes([cmd[4], cmd[5]]);
+                res[4..6].copy_from_slice(&self.gpio_direction.to_le_bytes());
+            }
+            // Get GPIO Pin Value
+            0x31 => {
+                res[4..6].copy_from_slice(&self.gpio_value.to_le_bytes());
+            }
+            // Set GPIO Pin Value
+            0x32 => {
+                self.gpio_value = u16::from_le_bytes([cmd[4], cmd[5]]);
+                res[4..6].copy_from_slice(&self.gpio_value.to_le_bytes());
+            }
+            // Get SPI Transfer Settings
+            0x41 => {
+                res[4..22].copy_from_slice(&self.spi_settings);
+            }
+            // Set SPI Transfer Settings
+            0x40 => {
+                self.spi_settings.copy_from_slice(&cmd[4..22]);
+                res[4..22].copy_from_slice(&self.spi_settings);
+            }
+            // Transfer SPI Data
+            //
+            // `cmd[1]` is the number of data bytes clocked out by this call (0 once the caller
+            // has nothing left to send, which is also how it signals "give me the last echo and
+            // wrap up"); `cmd[4..4 + cmd[1]]` is that data. The response carries the *previous*
+            // call's data echoed back (see `spi_pending`), `res[2]` the engine status, and
+            // `res[3]` how many of the `res[4..]` bytes are valid.
+            0x42 => {
+                let len = cmd[1] as usize;
+                let incoming = cmd[4..4 + len].to_vec();
+                let echo = std::mem::replace(&mut self.spi_pending, incoming);
+                res[2] = if len == 0 {
+                    SPI_STATUS_FINISHED
+                } else {
+                    SPI_STATUS_STARTED
+                };
+                res[3] = echo.len() as u8;
+                res[4..4 + echo.len()].copy_from_slice(&echo);
+            }
+            // Get Chip Settings (current)
+            0x20 => {
+                res[4..8].copy_from_slice(&self.chip_settings);
+            }
+            // Set Chip Settings (current)
+            0x21 => {
+                self.chip_settings.copy_from_slice(&cmd[4..8]);
+                res[4..8].copy_from_slice(&self.chip_settings);
+            }
+            // Get NVRAM Settings
+            0x61 => {
+                res[1] = cmd[1];
+                match cmd[1] {
+                    NVRAM_SPI_SETTINGS => res[4..22].copy_from_slice(&self.nvram_spi_settings),
+                    NVRAM_CHIP_SETTINGS => res[4..8].copy_from_slice(&self.nvram_chip_settings),
+                    _ => res[1] = ERR_UNKNOWN_COMMAND,
+                }
+            }
+            // Set NVRAM Settings
+            0x60 => {
+                res[1] = cmd[1];
+                match cmd[1] {
+                    NVRAM_SPI_SETTINGS => {
+                        self.nvram_spi_settings.copy_from_slice(&cmd[4..22]);
+                        res[4..22].copy_from_slice(&self.nvram_spi_settings);
+                    }
+                    NVRAM_CHIP_SETTINGS => {
+                        self.nvram_chip_settings.copy_from_slice(&cmd[4..8]);
+                        res[4..8].copy_from_slice(&self.nvram_chip_settings);
+                    }
+                    _ => res[1] = ERR_UNKNOWN_COMMAND,
+                }
+            }
+            // Read EEPROM
+            0x50 => {
+                let addr = cmd[1] as usize;
+                res[1] = cmd[1];
+                res[4] = *self.eeprom.get(addr).unwrap_or(&0xFF);
+            }
+            // Write EEPROM
+            0x51 => {
+                let addr = cmd[1] as usize;
+                res[1] = cmd[1];
+                if let Some(byte) = self.eeprom.get_mut(addr) {
+                    *byte = cmd[2];
+                }
+            }
+            unknown => {
+                res[1] = ERR_UNKNOWN_COMMAND;
+                let _ = unknown;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cmd(bytes: &[(usize, u8)]) -> Buffer {
+        let mut buf = [0u8; BUFFER_SIZE];
+        for &(index, value) in bytes {
+            buf[index] = value;
+        }
+        buf
+    }
+
+    #[test]
+    fn gpio_direction_round_trips() {
+        let mut emulator = Mcp2210Emulator::new();
+        assert_eq!(emulator.gpio_direction(), 0x01FF);
+
+        let mut res = [0u8; BUFFER_SIZE];
+        let mut request = cmd(&[(0, 0x34)]);
+        request[4..6].copy_from_slice(&0x0055u16.to_le_bytes());
+        emulator.command_response(&request, &mut res).unwrap();
+        assert_eq!(u16::from_le_bytes([res[4], res[5]]), 0x0055);
+        assert_eq!(emulator.gpio_direction(), 0x0055);
+
+        let mut res = [0u8; BUFFER_SIZE];
+        emulator
+            .command_response(&cmd(&[(0, 0x33)]), &mut res)
+            .unwrap();
+        assert_eq!(u16::from_le_bytes([res[4], res[5]]), 0x0055);
+    }
+
+    #[test]
+    fn gpio_value_round_trips() {
+        let mut emulator = Mcp2210Emulator::new();
+
+        let mut res = [0u8; BUFFER_SIZE];
+        let mut request = cmd(&[(0, 0x32)]);
+        request[4..6].copy_from_slice(&0x0001u16.to_le_bytes());
+        emulator.command_response(&request, &mut res).unwrap();
+        assert_eq!(emulator.gpio_value(), 0x0001);
+
+        let mut res = [0u8; BUFFER_SIZE];
+        emulator
+            .command_response(&cmd(&[(0, 0x31)]), &mut res)
+            .unwrap();
+        assert_eq!(u16::from_le_bytes([res[4], res[5]]), 0x0001);
+    }
+
+    #[test]
+    fn spi_transfer_echoes_back_with_a_one_call_delay() {
+        let mut emulator = Mcp2210Emulator::new();
+
+        // First call clocks the data out; the engine reports `Started` and there's nothing to
+        // echo back yet (no prior call's data is pending).
+        let mut first = cmd(&[(0, 0x42), (1, 3)]);
+        first[4..7].copy_from_slice(&[0xAA, 0xBB, 0xCC]);
+        let mut res = [0u8; BUFFER_SIZE];
+        emulator.command_response(&first, &mut res).unwrap();
+        assert_eq!(res[2], SPI_STATUS_STARTED);
+        assert_eq!(res[3], 0);
+
+        // Second call (no more data to send) finishes the transfer and echoes the first call's
+        // bytes back as the received data.
+        let second = cmd(&[(0, 0x42), (1, 0)]);
+        let mut res = [0u8; BUFFER_SIZE];
+        emulator.command_response(&second, &mut res).unwrap();
+        assert_eq!(res[2], SPI_STATUS_FINISHED);
+        assert_eq!(res[3], 3);
+        assert_eq!(&res[4..7], &[0xAA, 0xBB, 0xCC]);
+    }
+
+    #[test]
+    fn eeprom_round_trips() {
+        let mut emulator = Mcp2210Emulator::new();
+
+        let mut res = [0u8; BUFFER_SIZE];
+        emulator
+            .command_response(&cmd(&[(0, 0x51), (1, 10), (2, 0x42)]), &mut res)
+            .unwrap();
+
+        let mut res = [0u8; BUFFER_SIZE];
+        emulator
+            .command_response(&cmd(&[(0, 0x50), (1, 10)]), &mut res)
+            .unwrap();
+        assert_eq!(res[4], 0x42);
+    }
+
+    #[test]
+    fn nvram_settings_round_trip() {
+        let mut emulator = Mcp2210Emulator::new();
+
+        let mut request = cmd(&[(0, 0x60), (1, NVRAM_CHIP_SETTINGS)]);
+        request[4..8].copy_from_slice(&[1, 2, 3, 4]);
+        let mut res = [0u8; BUFFER_SIZE];
+        emulator.command_response(&request, &mut res).unwrap();
+        assert_eq!(&res[4..8], &[1, 2, 3, 4]);
+
+        let mut res = [0u8; BUFFER_SIZE];
+        emulator
+            .command_response(&cmd(&[(0, 0x61), (1, NVRAM_CHIP_SETTINGS)]), &mut res)
+            .unwrap();
+        assert_eq!(&res[4..8], &[1, 2, 3, 4]);
+    }
+}
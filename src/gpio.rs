@@ -0,0 +1,114 @@
+// SPDX-FileCopyrightText: 2018-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Per-pin `embedded-hal` digital I/O handles for the MCP2210's 9 GP pins.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use embedded_hal::digital::{self, ErrorType, InputPin, OutputPin};
+
+use crate::{CommandResponse, Mcp2210, Mcp2210Error};
+
+impl digital::Error for Mcp2210Error {
+    fn kind(&self) -> digital::ErrorKind {
+        digital::ErrorKind::Other
+    }
+}
+
+/// Number of GP pins exposed by the MCP2210 (`GP0`..=`GP8`).
+const GPIO_PIN_COUNT: usize = 9;
+
+/// A single GP pin, split out from a [`Mcp2210`] via [`Mcp2210::gpio`].
+///
+/// All 9 pins share the same underlying transport handle, so driving or reading one calls back
+/// into the `Mcp2210` that produced them through a shared, ref-counted cell.
+pub struct GpioPin<T = hidapi::HidDevice> {
+    device: Rc<RefCell<Mcp2210<T>>>,
+    index: u8,
+}
+
+impl<T: CommandResponse> Mcp2210<T> {
+    /// Splits this handle into its 9 GP pins, each implementing `embedded_hal::digital::OutputPin`
+    /// and `InputPin` by driving/reading a single bit of the GPIO value register.
+    ///
+    /// This consumes `self`; the pins jointly own the underlying transport via an `Rc<RefCell<_>>`.
+    pub fn gpio(self) -> [GpioPin<T>; GPIO_PIN_COUNT] {
+        let device = Rc::new(RefCell::new(self));
+        std::array::from_fn(|index| GpioPin {
+            device: device.clone(),
+            index: index as u8,
+        })
+    }
+}
+
+impl<T> GpioPin<T> {
+    fn mask(&self) -> u16 {
+        1 << self.index
+    }
+
+    /// Returns the transport handle this pin shares with its sibling `GpioPin`s, so it can also
+    /// be used to build a [`crate::Mcp2210SpiBus`] over the same physical device (e.g. for a
+    /// manual chip-select pin alongside the SPI bus) via
+    /// [`crate::Mcp2210SpiBus::from_shared`].
+    pub fn shared(&self) -> Rc<RefCell<Mcp2210<T>>> {
+        self.device.clone()
+    }
+}
+
+impl<T> ErrorType for GpioPin<T> {
+    type Error = Mcp2210Error;
+}
+
+impl<T: CommandResponse> OutputPin for GpioPin<T> {
+    fn set_low(&mut self) -> Result<(), Mcp2210Error> {
+        let mut device = self.device.borrow_mut();
+        let value = device.get_gpio_value()? & !self.mask();
+        device.set_gpio_value(value)
+    }
+
+    fn set_high(&mut self) -> Result<(), Mcp2210Error> {
+        let mut device = self.device.borrow_mut();
+        let value = device.get_gpio_value()? | self.mask();
+        device.set_gpio_value(value)
+    }
+}
+
+impl<T: CommandResponse> InputPin for GpioPin<T> {
+    fn is_high(&mut self) -> Result<bool, Mcp2210Error> {
+        let value = self.device.borrow_mut().get_gpio_value()?;
+        Ok(value & self.mask() != 0)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Mcp2210Error> {
+        Ok(!self.is_high()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Mcp2210Emulator;
+
+    #[test]
+    fn set_high_and_set_low_round_trip_through_the_emulator() {
+        let mut pins = Mcp2210::new(Mcp2210Emulator::new()).gpio();
+
+        pins[3].set_low().unwrap();
+        assert!(pins[3].is_low().unwrap());
+
+        pins[3].set_high().unwrap();
+        assert!(pins[3].is_high().unwrap());
+    }
+
+    #[test]
+    fn pins_share_the_same_underlying_gpio_value_register() {
+        let mut pins = Mcp2210::new(Mcp2210Emulator::new()).gpio();
+
+        pins[0].set_low().unwrap();
+        assert!(pins[0].is_low().unwrap());
+        // A different pin's state is unaffected by driving pin 0.
+        assert!(pins[1].is_high().unwrap());
+    }
+}
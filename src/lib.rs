@@ -3,10 +3,17 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 mod cmds;
+pub mod emulator;
+mod gpio;
+mod retry;
+mod spi_bus;
 mod types;
 mod utils;
 
 pub use crate::cmds::*;
+pub use crate::gpio::*;
+pub use crate::retry::*;
+pub use crate::spi_bus::*;
 pub use crate::types::*;
 
 use hidapi::{DeviceInfo, HidApi, HidDevice, HidError, HidResult};
@@ -15,6 +22,7 @@ use std::error::Error;
 use std::ffi::{CString, NulError};
 use std::fmt;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
 #[derive(Debug)]
 pub enum Mcp2210Error {
@@ -28,6 +36,7 @@ pub enum Mcp2210Error {
     StringSize(usize),
     PayloadSize(usize),
     TransferStatus(SpiTransferStatus),
+    Timeout,
 
     // MCP2210 error codes
     EepromWrite,            // 0xFA
@@ -69,6 +78,7 @@ impl fmt::Display for Mcp2210Error {
                 size
             ),
             TransferStatus(status) => write!(f, "Unexpected SPI transfer status {:?}", status),
+            Timeout => write!(f, "Retry policy deadline exceeded"),
             EepromWrite => write!(f, "EEPROM write failure"),
             AccessDenied => write!(f, "Access denied"),
             AccessRejected => write!(f, "Access rejected"),
@@ -90,30 +100,108 @@ impl Error for Mcp2210Error {
     }
 }
 
-const BUFFER_SIZE: usize = 64;
+pub(crate) const BUFFER_SIZE: usize = 64;
 
 pub type Buffer = [u8; BUFFER_SIZE];
 
 pub const MAX_BIT_RATE: u32 = 12_000_000;
 
-pub struct Mcp2210 {
-    device: HidDevice,
+/// A handle to a MCP2210, generic over the transport used to exchange command/response reports.
+///
+/// The transport `T` only needs to implement [`CommandResponse`]; this is the sole I/O seam the
+/// chip's commands are built on. The default `T = HidDevice` talks to real hardware through
+/// `hidapi`, but any other backend (e.g. [`crate::emulator::Mcp2210Emulator`]) can stand in for it,
+/// which is what makes the rest of this crate's logic testable without physical hardware.
+pub struct Mcp2210<T = HidDevice> {
+    device: T,
+    retry_policy: RetryPolicy,
 }
 
-impl CommandResponse for Mcp2210 {
+impl CommandResponse for HidDevice {
     fn command_response(&mut self, cmd: &Buffer, res: &mut Buffer) -> HidResult<()> {
         let data_to_write = &[[0x00].to_vec(), cmd.to_vec()].concat();
         // At this point, length of data_to_write will be BUFFER_SIZE + 1 == 65 or smaller and responses
         // from the MCP2210 are always BUFFER_SIZE. Therefore, this should only take single reports and
         // these asserts should be good assumptions.
-        assert_eq!(self.device.write(data_to_write)?, data_to_write.len());
-        assert_eq!(self.device.read(res)?, BUFFER_SIZE);
+        assert_eq!(self.write(data_to_write)?, data_to_write.len());
+        assert_eq!(self.read(res)?, BUFFER_SIZE);
         Ok(())
     }
 }
 
+impl<T: CommandResponse> CommandResponse for Mcp2210<T> {
+    fn command_response(&mut self, cmd: &Buffer, res: &mut Buffer) -> HidResult<()> {
+        self.device.command_response(cmd, res)
+    }
+}
+
+impl<T> Mcp2210<T> {
+    /// Wraps an already-open transport `device` as a MCP2210 handle.
+    ///
+    /// Use this to drive a [`crate::emulator::Mcp2210Emulator`] or any other custom
+    /// [`CommandResponse`] backend instead of real hardware.
+    pub fn new(device: T) -> Mcp2210<T> {
+        Mcp2210 {
+            device,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    /// The [`RetryPolicy`] currently applied to retryable transfer errors (`Busy`,
+    /// `Unavailable`, `AccessDeniedRetry`).
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// Replaces the [`RetryPolicy`] applied to retryable transfer errors.
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+}
+
+static SHARED_HID_API: Mutex<Option<HidApi>> = Mutex::new(None);
+
+/// Runs `f` with the process-wide `HidApi` context, initializing it on first use.
+///
+/// Every `hidapi::HidApi::new()` call performs a full device enumeration, so reusing this
+/// context (as [`Mcp2210::open_path`] and friends do) instead of creating a fresh one lets an
+/// application that opens many MCP2210s, or repeatedly scans for them, pay that cost once.
+///
+/// The context is locked for the duration of `f`, so newly attached devices never appear
+/// mid-call; call [`refresh_shared_hid_api`] between calls to pick them up.
+///
+/// # Panics
+///
+/// Under the hood this calls the `hidapi::HidApi::new()` function which panics if hidapi is already
+/// initialized in "without enumerate" mode (i.e. if `HidApi::new_without_enumerate()` has been called before).
+/// This would also cause a later call to `HidApi::new_without_enumberate()` to panic.
+pub fn with_shared_hid_api<R>(
+    f: impl FnOnce(&HidApi) -> Result<R, Mcp2210Error>,
+) -> Result<R, Mcp2210Error> {
+    let mut guard = SHARED_HID_API.lock().unwrap();
+    if guard.is_none() {
+        *guard = Some(HidApi::new().map_err(Mcp2210Error::Hid)?);
+    }
+    f(guard.as_ref().unwrap())
+}
+
+/// Re-enumerates devices on the context shared by [`with_shared_hid_api`], so MCP2210s attached
+/// after the context was created (or after the last refresh) become visible to later
+/// [`scan_devices`]/[`Mcp2210::open_path`]-family calls. Initializes the shared context first if
+/// it hasn't been used yet.
+pub fn refresh_shared_hid_api() -> Result<(), Mcp2210Error> {
+    let mut guard = SHARED_HID_API.lock().unwrap();
+    match guard.as_mut() {
+        Some(context) => context.refresh_devices().map_err(Mcp2210Error::Hid),
+        None => {
+            *guard = Some(HidApi::new().map_err(Mcp2210Error::Hid)?);
+            Ok(())
+        }
+    }
+}
+
 impl Mcp2210 {
-    /// Opens a MCP2210 by path
+    /// Opens a MCP2210 by path, using the [`with_shared_hid_api`] context.
     ///
     /// # Panics
     ///
@@ -121,7 +209,17 @@ impl Mcp2210 {
     /// initialized in "without enumerate" mode (i.e. if `HidApi::new_without_enumerate()` has been called before).
     /// This would also cause a later call to `HidApi::new_without_enumberate()` to panic.
     pub fn open_path<P: AsRef<Path>>(path: P) -> Result<Mcp2210, Mcp2210Error> {
-        // Path to CString
+        with_shared_hid_api(|context| Mcp2210::open_path_with(context, path))
+    }
+
+    /// Opens a MCP2210 by path, using a caller-owned `HidApi` context.
+    ///
+    /// Prefer this over [`Mcp2210::open_path`] when opening several devices, so the context's
+    /// device enumeration is only paid for once.
+    pub fn open_path_with<P: AsRef<Path>>(
+        context: &HidApi,
+        path: P,
+    ) -> Result<Mcp2210, Mcp2210Error> {
         let path_cstr = CString::new(
             path.as_ref()
                 .to_str()
@@ -129,12 +227,15 @@ impl Mcp2210 {
         )
         .map_err(Mcp2210Error::NulCharInPath)?;
 
-        let context = HidApi::new().map_err(Mcp2210Error::Hid)?;
         let device = context.open_path(&path_cstr).map_err(Mcp2210Error::Hid)?;
-        Ok(Mcp2210 { device })
+        Ok(Mcp2210 {
+            device,
+            retry_policy: RetryPolicy::default(),
+        })
     }
 
-    /// Opens a MCP2210 using a Vendor ID (VID), Product ID (PID) and a serial number.
+    /// Opens a MCP2210 using a Vendor ID (VID), Product ID (PID) and a serial number, using the
+    /// [`with_shared_hid_api`] context.
     ///
     /// # Panics
     ///
@@ -142,15 +243,31 @@ impl Mcp2210 {
     /// initialized in "without enumerate" mode (i.e. if `HidApi::new_without_enumerate()` has been called before).
     /// This would also cause a later call to `HidApi::new_without_enumberate()` to panic.
     pub fn open_serial(vid: u16, pid: u16, sn: &str) -> Result<Mcp2210, Mcp2210Error> {
-        let context = HidApi::new().map_err(Mcp2210Error::Hid)?;
+        with_shared_hid_api(|context| Mcp2210::open_serial_with(context, vid, pid, sn))
+    }
+
+    /// Opens a MCP2210 using a Vendor ID (VID), Product ID (PID) and a serial number, using a
+    /// caller-owned `HidApi` context.
+    ///
+    /// Prefer this over [`Mcp2210::open_serial`] when opening several devices, so the context's
+    /// device enumeration is only paid for once.
+    pub fn open_serial_with(
+        context: &HidApi,
+        vid: u16,
+        pid: u16,
+        sn: &str,
+    ) -> Result<Mcp2210, Mcp2210Error> {
         let device = context
             .open_serial(vid, pid, sn)
             .map_err(Mcp2210Error::Hid)?;
-        Ok(Mcp2210 { device })
+        Ok(Mcp2210 {
+            device,
+            retry_policy: RetryPolicy::default(),
+        })
     }
 
     /// Opens a MCP2210 using a &DeviceInfo whice you may optain with the `scan_devices_with_filter()` or
-    /// `scan_devices()` functions.
+    /// `scan_devices()` functions, using the [`with_shared_hid_api`] context.
     ///
     /// # Panics
     ///
@@ -158,13 +275,28 @@ impl Mcp2210 {
     /// initialized in "without enumerate" mode (i.e. if `HidApi::new_without_enumerate()` has been called before).
     /// This would also cause a later call to `HidApi::new_without_enumberate()` to panic.
     pub fn open_device(device_info: &DeviceInfo) -> Result<Mcp2210, Mcp2210Error> {
-        let context = HidApi::new().map_err(Mcp2210Error::Hid)?;
+        with_shared_hid_api(|context| Mcp2210::open_device_with(context, device_info))
+    }
+
+    /// Opens a MCP2210 using a `&DeviceInfo` and a caller-owned `HidApi` context.
+    ///
+    /// Prefer this over [`Mcp2210::open_device`] when opening several devices, so the context's
+    /// device enumeration is only paid for once.
+    pub fn open_device_with(
+        context: &HidApi,
+        device_info: &DeviceInfo,
+    ) -> Result<Mcp2210, Mcp2210Error> {
         let device = device_info
-            .open_device(&context)
+            .open_device(context)
             .map_err(Mcp2210Error::Hid)?;
-        Ok(Mcp2210 { device })
+        Ok(Mcp2210 {
+            device,
+            retry_policy: RetryPolicy::default(),
+        })
     }
+}
 
+impl<T: CommandResponse> Mcp2210<T> {
     pub fn spi_transfer_to_end(
         &mut self,
         mut data: &[u8],
@@ -173,7 +305,8 @@ impl Mcp2210 {
         let mut res: Buffer = [0; 64];
         {
             let len = min(data.len(), 60);
-            let res = self.spi_transfer(&data[..len], &mut res)?;
+            let chunk = &data[..len];
+            let res = self.retry(|device| device.spi_transfer(chunk, &mut res))?;
             data = &data[len..];
             if res.status != SpiTransferStatus::Started {
                 return Err(Mcp2210Error::TransferStatus(res.status));
@@ -181,23 +314,23 @@ impl Mcp2210 {
         }
         loop {
             let len = min(data.len(), 60);
-            match self.spi_transfer(&data[..len], &mut res) {
-                Ok(res) => {
-                    data = &data[len..];
-                    buf.extend(res.data);
-                    if res.status == SpiTransferStatus::Finished {
-                        break;
-                    }
-                }
-                Err(Mcp2210Error::Busy) => (),
-                Err(err) => return Err(err),
+            let chunk = &data[..len];
+            let res = self.retry(|device| device.spi_transfer(chunk, &mut res))?;
+            data = &data[len..];
+            buf.extend(res.data);
+            if res.status == SpiTransferStatus::Finished {
+                break;
             }
         }
         Ok(())
     }
 }
 
-/// Scans devices for the default vendor ID and product ID that the MCP2210 comes with
+/// Scans devices for the default vendor ID and product ID that the MCP2210 comes with, using
+/// the [`with_shared_hid_api`] context.
+///
+/// This only sees devices enumerated by the shared context's last refresh; call
+/// [`refresh_shared_hid_api`] first to pick up devices attached since then.
 ///
 /// # Panics
 ///
@@ -208,7 +341,10 @@ pub fn scan_devices() -> Result<Vec<DeviceInfo>, Mcp2210Error> {
     scan_devices_with_filter(|d| d.vendor_id() == 0x04d8 && d.product_id() == 0x00de)
 }
 
-/// Scans devices with a provided filter
+/// Scans devices with a provided filter, using the [`with_shared_hid_api`] context.
+///
+/// This only sees devices enumerated by the shared context's last refresh; call
+/// [`refresh_shared_hid_api`] first to pick up devices attached since then.
 ///
 /// # Panics
 ///
@@ -217,9 +353,19 @@ pub fn scan_devices() -> Result<Vec<DeviceInfo>, Mcp2210Error> {
 /// This would also cause a later call to `HidApi::new_without_enumberate()` to panic.
 pub fn scan_devices_with_filter<F: FnMut(&DeviceInfo) -> bool>(
     mut f: F,
+) -> Result<Vec<DeviceInfo>, Mcp2210Error> {
+    with_shared_hid_api(|context| scan_devices_with_filter_with(context, &mut f))
+}
+
+/// Scans devices with a provided filter, using a caller-owned `HidApi` context.
+///
+/// Prefer this over [`scan_devices_with_filter`] when scanning repeatedly, so the context's
+/// device enumeration is only paid for once.
+pub fn scan_devices_with_filter_with<F: FnMut(&DeviceInfo) -> bool>(
+    context: &HidApi,
+    mut f: F,
 ) -> Result<Vec<DeviceInfo>, Mcp2210Error> {
     let mut results = Vec::new();
-    let context = HidApi::new().map_err(Mcp2210Error::Hid)?;
     let devices = context.device_list();
     for d in devices {
         if f(d) {
@@ -0,0 +1,156 @@
+// SPDX-FileCopyrightText: 2018-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Retry/timeout policy for the transient error codes the MCP2210 returns while the SPI bus is
+//! contended (`Busy`, `Unavailable`, `AccessDeniedRetry`), so callers no longer spin forever
+//! waiting them out.
+
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crate::{CommandResponse, Mcp2210, Mcp2210Error};
+
+/// Governs how [`Mcp2210`] retries commands that fail with a transient error code.
+///
+/// Non-retryable errors (anything other than [`Mcp2210Error::Busy`],
+/// [`Mcp2210Error::Unavailable`] and [`Mcp2210Error::AccessDeniedRetry`]) are always propagated
+/// immediately, regardless of this policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts (including the first) before giving up; the last retryable
+    /// error is then returned as-is.
+    pub max_attempts: u32,
+    /// Overall deadline across all attempts, starting from the first one.
+    pub total_timeout: Duration,
+    /// Delay between a failed attempt and the next retry.
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Never retries: the first transient error is returned as-is.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            total_timeout: Duration::MAX,
+            backoff: Duration::ZERO,
+        }
+    }
+
+    pub(crate) fn is_retryable(err: &Mcp2210Error) -> bool {
+        matches!(
+            err,
+            Mcp2210Error::Busy | Mcp2210Error::Unavailable | Mcp2210Error::AccessDeniedRetry
+        )
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Makes up to 50 attempts over at most 1 second, backing off 10ms between attempts.
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 50,
+            total_timeout: Duration::from_secs(1),
+            backoff: Duration::from_millis(10),
+        }
+    }
+}
+
+impl<T: CommandResponse> Mcp2210<T> {
+    /// Runs `op` against `self`, retrying per [`Mcp2210::retry_policy`] as long as it fails with
+    /// a retryable [`Mcp2210Error`]. Non-retryable errors propagate immediately. Running out of
+    /// `max_attempts` returns the last retryable error as-is; running past `total_timeout`
+    /// returns [`Mcp2210Error::Timeout`] instead.
+    pub(crate) fn retry<F, R>(&mut self, mut op: F) -> Result<R, Mcp2210Error>
+    where
+        F: FnMut(&mut Self) -> Result<R, Mcp2210Error>,
+    {
+        let policy = self.retry_policy;
+        // `checked_add` avoids panicking on `Instant + Duration` overflow for huge/`MAX`
+        // timeouts; `None` is treated as "no deadline".
+        let deadline = Instant::now().checked_add(policy.total_timeout);
+        for attempt in 1.. {
+            match op(self) {
+                Ok(value) => return Ok(value),
+                Err(err) if RetryPolicy::is_retryable(&err) => {
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        return Err(Mcp2210Error::Timeout);
+                    }
+                    if attempt >= policy.max_attempts {
+                        return Err(err);
+                    }
+                    let remaining = deadline
+                        .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+                        .unwrap_or(policy.backoff);
+                    sleep(policy.backoff.min(remaining));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        unreachable!()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Mcp2210Emulator;
+    use crate::{Buffer, BUFFER_SIZE};
+    use hidapi::HidResult;
+
+    /// A [`CommandResponse`] backend that reports `Busy` for its first `fails_remaining` calls
+    /// before delegating to `inner`, so retry behavior can be exercised without real hardware.
+    struct FlakyBackend<T> {
+        inner: T,
+        fails_remaining: u32,
+    }
+
+    impl<T: CommandResponse> CommandResponse for FlakyBackend<T> {
+        fn command_response(&mut self, cmd: &Buffer, res: &mut Buffer) -> HidResult<()> {
+            if self.fails_remaining > 0 {
+                self.fails_remaining -= 1;
+                *res = [0; BUFFER_SIZE];
+                res[0] = cmd[0];
+                res[1] = 0xF8; // Busy
+                return Ok(());
+            }
+            self.inner.command_response(cmd, res)
+        }
+    }
+
+    #[test]
+    fn retry_recovers_from_transient_busy_errors() {
+        let backend = FlakyBackend {
+            inner: Mcp2210Emulator::new(),
+            fails_remaining: 3,
+        };
+        let mut device = Mcp2210::new(backend);
+        device.set_retry_policy(RetryPolicy {
+            max_attempts: 10,
+            total_timeout: Duration::from_secs(1),
+            backoff: Duration::from_millis(1),
+        });
+
+        let mut buf = Vec::new();
+        device.spi_transfer_to_end(&[0xAA], &mut buf).unwrap();
+        assert_eq!(buf, vec![0xAA]);
+    }
+
+    #[test]
+    fn giving_up_after_max_attempts_returns_the_real_error_not_timeout() {
+        let backend = FlakyBackend {
+            inner: Mcp2210Emulator::new(),
+            fails_remaining: u32::MAX,
+        };
+        let mut device = Mcp2210::new(backend);
+        device.set_retry_policy(RetryPolicy {
+            max_attempts: 3,
+            total_timeout: Duration::from_secs(10),
+            backoff: Duration::from_millis(1),
+        });
+
+        let mut buf = Vec::new();
+        let err = device.spi_transfer_to_end(&[0xAA], &mut buf).unwrap_err();
+        assert!(matches!(err, Mcp2210Error::Busy));
+    }
+}
@@ -0,0 +1,147 @@
+// SPDX-FileCopyrightText: 2018-2022 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! `embedded-hal` SPI trait implementations for [`Mcp2210`], so the chip can be used as a
+//! host-side SPI master by any driver crate written against `embedded-hal` (e.g. `max116xx-10bit`).
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::thread::sleep;
+use std::time::Duration;
+
+use embedded_hal::spi::{Error, ErrorKind, ErrorType, Operation, SpiBus, SpiDevice};
+
+use crate::{CommandResponse, Mcp2210, Mcp2210Error};
+
+impl Error for Mcp2210Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+/// Wraps a [`Mcp2210`] as an `embedded-hal` [`SpiBus`]/[`SpiDevice`].
+///
+/// Every `read`/`write`/`transfer`/`transfer_in_place` call is forwarded to
+/// [`Mcp2210::spi_transfer_to_end`], which already chunks the payload at the 60-byte limit
+/// the chip enforces per report and pumps the `Started`/`Finished` transfer status loop.
+pub struct Mcp2210SpiBus<T = hidapi::HidDevice> {
+    device: Rc<RefCell<Mcp2210<T>>>,
+}
+
+impl<T> Mcp2210SpiBus<T> {
+    /// Wraps `device` as an `embedded-hal` SPI bus.
+    pub fn new(device: Mcp2210<T>) -> Self {
+        Mcp2210SpiBus {
+            device: Rc::new(RefCell::new(device)),
+        }
+    }
+
+    /// Wraps a transport handle already shared with [`crate::GpioPin`]s (see
+    /// [`crate::GpioPin::shared`]), so a manually driven chip-select pin and the SPI bus can
+    /// operate on the same physical device.
+    pub fn from_shared(device: Rc<RefCell<Mcp2210<T>>>) -> Self {
+        Mcp2210SpiBus { device }
+    }
+
+    /// Unwraps this bus, returning the underlying [`Mcp2210`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the transport handle is still shared with a [`crate::GpioPin`] or another
+    /// `Mcp2210SpiBus` (i.e. this bus was built via [`Mcp2210SpiBus::from_shared`] and the share
+    /// outlives this call).
+    pub fn into_inner(self) -> Mcp2210<T> {
+        Rc::try_unwrap(self.device)
+            .unwrap_or_else(|_| panic!("Mcp2210SpiBus::into_inner: device handle is still shared"))
+            .into_inner()
+    }
+}
+
+impl<T> ErrorType for Mcp2210SpiBus<T> {
+    type Error = Mcp2210Error;
+}
+
+impl<T: CommandResponse> SpiBus<u8> for Mcp2210SpiBus<T> {
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Mcp2210Error> {
+        let zeros = vec![0u8; words.len()];
+        let mut buf = Vec::with_capacity(words.len());
+        self.device
+            .borrow_mut()
+            .spi_transfer_to_end(&zeros, &mut buf)?;
+        words.copy_from_slice(&buf[..words.len()]);
+        Ok(())
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Mcp2210Error> {
+        let mut buf = Vec::new();
+        self.device.borrow_mut().spi_transfer_to_end(words, &mut buf)
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Mcp2210Error> {
+        // `SpiBus::transfer` clocks `max(read.len(), write.len())` words, writing zeros once
+        // `write` is exhausted, so a longer `read` still gets its tail driven on the bus.
+        let mut padded_write = write.to_vec();
+        padded_write.resize(read.len().max(write.len()), 0);
+        let mut buf = Vec::with_capacity(padded_write.len());
+        self.device
+            .borrow_mut()
+            .spi_transfer_to_end(&padded_write, &mut buf)?;
+        let len = read.len().min(buf.len());
+        read[..len].copy_from_slice(&buf[..len]);
+        Ok(())
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Mcp2210Error> {
+        let mut buf = Vec::with_capacity(words.len());
+        self.device
+            .borrow_mut()
+            .spi_transfer_to_end(words, &mut buf)?;
+        words.copy_from_slice(&buf[..words.len()]);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Mcp2210Error> {
+        // Every write above already blocks until the chip reports the transfer finished.
+        Ok(())
+    }
+}
+
+impl<T: CommandResponse> SpiDevice<u8> for Mcp2210SpiBus<T> {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Mcp2210Error> {
+        for op in operations {
+            match op {
+                Operation::Read(words) => self.read(words)?,
+                Operation::Write(words) => self.write(words)?,
+                Operation::Transfer(read, write) => self.transfer(read, write)?,
+                Operation::TransferInPlace(words) => self.transfer_in_place(words)?,
+                Operation::DelayNs(ns) => sleep(Duration::from_nanos(*ns as u64)),
+            }
+        }
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Mcp2210Emulator;
+
+    #[test]
+    fn transfer_in_place_echoes_data_back_through_the_emulator() {
+        let mut bus = Mcp2210SpiBus::new(Mcp2210::new(Mcp2210Emulator::new()));
+        let mut words = [0xAA, 0xBB, 0xCC];
+        bus.transfer_in_place(&mut words).unwrap();
+        assert_eq!(words, [0xAA, 0xBB, 0xCC]);
+    }
+
+    #[test]
+    fn from_shared_reuses_the_transport_a_gpio_pin_shares() {
+        let pins = Mcp2210::new(Mcp2210Emulator::new()).gpio();
+        let shared = pins[0].shared();
+        let mut bus = Mcp2210SpiBus::from_shared(shared);
+        let mut words = [0x01];
+        bus.transfer_in_place(&mut words).unwrap();
+        assert_eq!(words, [0x01]);
+    }
+}